@@ -0,0 +1,241 @@
+use algebra::Field;
+use r1cs_core::{ConstraintSystem, SynthesisError};
+use r1cs_std::prelude::*;
+use zexe_cp::crh::{FixedLengthCRH, FixedLengthCRHGadget};
+
+use single_step_avd::SingleStepAVD;
+
+/// Circuit-side counterpart of [`crate::history_tree::DigestCombiner`]: enforces that
+/// `result` is the combiner's `evaluate` applied to the SSAVD digest, history-tree root,
+/// and epoch gadgets, using the same number of `HG` hash gadget invocations as the
+/// matching native combiner.
+pub trait DigestCombinerGadget<SSAVD: SingleStepAVD, H: FixedLengthCRH, ConstraintF: Field> {
+    type HGadget: FixedLengthCRHGadget<H, ConstraintF>;
+
+    fn enforce_evaluate<CS: ConstraintSystem<ConstraintF>>(
+        cs: CS,
+        parameters: &<Self::HGadget as FixedLengthCRHGadget<H, ConstraintF>>::ParametersGadget,
+        ssavd_digest_bytes: &[UInt8],
+        history_tree_digest: &<Self::HGadget as FixedLengthCRHGadget<H, ConstraintF>>::OutputGadget,
+        epoch_bytes: &[UInt8],
+        result: &<Self::HGadget as FixedLengthCRHGadget<H, ConstraintF>>::OutputGadget,
+    ) -> Result<(), SynthesisError>;
+}
+
+fn hash_bytes<H, HG, ConstraintF, CS>(
+    mut cs: CS,
+    parameters: &HG::ParametersGadget,
+    input: &[UInt8],
+) -> Result<HG::OutputGadget, SynthesisError>
+where
+    ConstraintF: Field,
+    H: FixedLengthCRH,
+    HG: FixedLengthCRHGadget<H, ConstraintF>,
+    CS: ConstraintSystem<ConstraintF>,
+{
+    HG::check_evaluation_gadget(cs.ns(|| "hash"), parameters, input)
+}
+
+// Mirrors the native combiners' buffer sizing (see 283745b): `H` expects exactly
+// `H::INPUT_SIZE_BITS / 8` bytes, so a shorter concatenation (the normal case -- that's the
+// whole point of fitting everything in one permutation) must be zero-padded before being
+// passed to `check_evaluation_gadget`, or the in-circuit hash is computed over a different
+// byte string than `DigestCombiner::evaluate` produces natively. A too-long concatenation
+// can't be silently truncated without dropping real input, so it's rejected instead.
+fn pad_to_input_size<H: FixedLengthCRH>(
+    mut input: Vec<UInt8>,
+) -> Result<Vec<UInt8>, SynthesisError> {
+    let input_bytes = H::INPUT_SIZE_BITS / 8;
+    if input.len() > input_bytes {
+        return Err(SynthesisError::Unsatisfiable);
+    }
+    input.resize(input_bytes, UInt8::constant(0));
+    Ok(input)
+}
+
+pub struct PedersenDigestCombinerGadget<HG> {
+    _hash_gadget: std::marker::PhantomData<HG>,
+}
+
+impl<SSAVD, H, HG, ConstraintF> DigestCombinerGadget<SSAVD, H, ConstraintF>
+    for PedersenDigestCombinerGadget<HG>
+where
+    SSAVD: SingleStepAVD,
+    H: FixedLengthCRH,
+    HG: FixedLengthCRHGadget<H, ConstraintF>,
+    ConstraintF: Field,
+{
+    type HGadget = HG;
+
+    fn enforce_evaluate<CS: ConstraintSystem<ConstraintF>>(
+        mut cs: CS,
+        parameters: &HG::ParametersGadget,
+        ssavd_digest_bytes: &[UInt8],
+        history_tree_digest: &HG::OutputGadget,
+        epoch_bytes: &[UInt8],
+        result: &HG::OutputGadget,
+    ) -> Result<(), SynthesisError> {
+        // Mirrors `PedersenDigestCombiner::evaluate`: hash the two digests together, then
+        // hash the epoch into that result -- two `HG` invocations.
+        let mut digests_input = ssavd_digest_bytes.to_vec();
+        digests_input.extend_from_slice(&history_tree_digest.to_bytes(cs.ns(|| "root_to_bytes"))?);
+        let digests_input = pad_to_input_size::<H>(digests_input)?;
+        let digests_hash = hash_bytes::<H, HG, ConstraintF, _>(
+            cs.ns(|| "hash_digests"),
+            parameters,
+            &digests_input,
+        )?;
+
+        let mut epoch_input = epoch_bytes.to_vec();
+        epoch_input.extend_from_slice(&digests_hash.to_bytes(cs.ns(|| "digests_hash_to_bytes"))?);
+        let epoch_input = pad_to_input_size::<H>(epoch_input)?;
+        let final_hash =
+            hash_bytes::<H, HG, ConstraintF, _>(cs.ns(|| "hash_epoch"), parameters, &epoch_input)?;
+
+        final_hash.enforce_equal(cs.ns(|| "check_result"), result)
+    }
+}
+
+pub struct PoseidonDigestCombinerGadget<HG> {
+    _hash_gadget: std::marker::PhantomData<HG>,
+}
+
+impl<SSAVD, H, HG, ConstraintF> DigestCombinerGadget<SSAVD, H, ConstraintF>
+    for PoseidonDigestCombinerGadget<HG>
+where
+    SSAVD: SingleStepAVD,
+    H: FixedLengthCRH,
+    HG: FixedLengthCRHGadget<H, ConstraintF>,
+    ConstraintF: Field,
+{
+    type HGadget = HG;
+
+    fn enforce_evaluate<CS: ConstraintSystem<ConstraintF>>(
+        mut cs: CS,
+        parameters: &HG::ParametersGadget,
+        ssavd_digest_bytes: &[UInt8],
+        history_tree_digest: &HG::OutputGadget,
+        epoch_bytes: &[UInt8],
+        result: &HG::OutputGadget,
+    ) -> Result<(), SynthesisError> {
+        // Mirrors `PoseidonDigestCombiner::evaluate`: a single sponge absorbing all three
+        // inputs at once, so only one `HG` invocation is needed instead of two.
+        let mut input = ssavd_digest_bytes.to_vec();
+        input.extend_from_slice(&history_tree_digest.to_bytes(cs.ns(|| "root_to_bytes"))?);
+        input.extend_from_slice(epoch_bytes);
+        let input = pad_to_input_size::<H>(input)?;
+        let final_hash =
+            hash_bytes::<H, HG, ConstraintF, _>(cs.ns(|| "hash_all"), parameters, &input)?;
+
+        final_hash.enforce_equal(cs.ns(|| "check_result"), result)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use algebra::ed_on_bls12_381::{EdwardsAffine as JubJub, EdwardsParameters, Fq};
+    use r1cs_core::TestConstraintSystem;
+    use r1cs_std::{fields::fp::FpGadget, groups::curves::twisted_edwards::AffineGadget};
+    use rand::{rngs::StdRng, SeedableRng};
+    use zexe_cp::crh::pedersen::{constraints::PedersenCRHGadget, PedersenCRH, PedersenWindow};
+
+    use crate::history_tree::{DigestCombiner, PoseidonDigestCombiner};
+    use crypto_primitives::sparse_merkle_tree::{MerkleDepth, MerkleTreeParameters};
+    use single_step_avd::merkle_tree_avd::{MerkleTreeAVD, MerkleTreeAVDParameters};
+
+    #[derive(Clone)]
+    pub struct Window4x256;
+
+    impl PedersenWindow for Window4x256 {
+        const WINDOW_SIZE: usize = 4;
+        const NUM_WINDOWS: usize = 256;
+    }
+
+    type H = PedersenCRH<JubJub, Window4x256>;
+    type JubJubGadget = AffineGadget<EdwardsParameters, Fq, FpGadget<Fq>>;
+    type HG = PedersenCRHGadget<JubJub, Fq, JubJubGadget>;
+
+    #[derive(Clone)]
+    pub struct MerkleTreeTestParameters;
+
+    impl MerkleTreeParameters for MerkleTreeTestParameters {
+        const DEPTH: MerkleDepth = 4;
+        type H = H;
+    }
+
+    #[derive(Clone)]
+    pub struct MerkleTreeAVDTestParameters;
+
+    impl MerkleTreeAVDParameters for MerkleTreeAVDTestParameters {
+        const MAX_UPDATE_BATCH_SIZE: u64 = 4;
+        const MAX_OPEN_ADDRESSING_PROBES: u8 = 2;
+        type MerkleTreeParameters = MerkleTreeTestParameters;
+    }
+
+    type TestMerkleTreeAVD = MerkleTreeAVD<MerkleTreeAVDTestParameters>;
+
+    // Checks that `PoseidonDigestCombinerGadget::enforce_evaluate` accepts exactly the value
+    // `PoseidonDigestCombiner::evaluate` computes natively on the same inputs -- the two must
+    // hash the same (now zero-padded) byte string, or proofs built against this circuit would
+    // not correspond to the real digest.
+    #[test]
+    fn poseidon_digest_combiner_gadget_matches_native_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let parameters = <H as FixedLengthCRH>::setup(&mut rng).unwrap();
+        let ssavd_digest = <TestMerkleTreeAVD as SingleStepAVD>::Digest::default();
+        let history_tree_digest = <H as FixedLengthCRH>::Output::default();
+        let epoch: u64 = 7;
+
+        let expected = <PoseidonDigestCombiner as DigestCombiner<TestMerkleTreeAVD, H>>::evaluate(
+            &parameters,
+            &ssavd_digest,
+            &history_tree_digest,
+            &epoch,
+        )
+        .unwrap();
+
+        let mut cs = TestConstraintSystem::<Fq>::new();
+        let parameters_gadget =
+            <<HG as FixedLengthCRHGadget<H, Fq>>::ParametersGadget as AllocGadget<
+                <H as FixedLengthCRH>::Parameters,
+                Fq,
+            >>::alloc(cs.ns(|| "parameters"), || Ok(parameters.clone()))
+            .unwrap();
+
+        let mut ssavd_digest_bytes_native = Vec::new();
+        ssavd_digest.write(&mut ssavd_digest_bytes_native).unwrap();
+        let ssavd_digest_bytes =
+            UInt8::alloc_vec(cs.ns(|| "ssavd_digest"), &ssavd_digest_bytes_native).unwrap();
+
+        let history_tree_digest_gadget =
+            <<HG as FixedLengthCRHGadget<H, Fq>>::OutputGadget as AllocGadget<
+                <H as FixedLengthCRH>::Output,
+                Fq,
+            >>::alloc(cs.ns(|| "history_tree_digest"), || {
+                Ok(history_tree_digest.clone())
+            })
+            .unwrap();
+
+        let epoch_bytes = UInt8::alloc_vec(cs.ns(|| "epoch"), &epoch.to_le_bytes()).unwrap();
+
+        let result_gadget =
+            <<HG as FixedLengthCRHGadget<H, Fq>>::OutputGadget as AllocGadget<
+                <H as FixedLengthCRH>::Output,
+                Fq,
+            >>::alloc(cs.ns(|| "result"), || Ok(expected.clone()))
+            .unwrap();
+
+        <PoseidonDigestCombinerGadget<HG> as DigestCombinerGadget<TestMerkleTreeAVD, H, Fq>>::enforce_evaluate(
+            cs.ns(|| "enforce_evaluate"),
+            &parameters_gadget,
+            &ssavd_digest_bytes,
+            &history_tree_digest_gadget,
+            &epoch_bytes,
+            &result_gadget,
+        )
+        .unwrap();
+
+        assert!(cs.is_satisfied());
+    }
+}
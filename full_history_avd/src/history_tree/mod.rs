@@ -1,4 +1,4 @@
-use algebra::bytes::ToBytes;
+use algebra::bytes::{FromBytes, ToBytes};
 use zexe_cp::crh::FixedLengthCRH;
 
 use crypto_primitives::sparse_merkle_tree::{
@@ -8,29 +8,176 @@ use single_step_avd::SingleStepAVD;
 
 use crate::Error;
 
-use std::{collections::HashMap, hash::Hash, io::{Write, Cursor}};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+    io::{self, Cursor, Read, Result as IoResult, Write},
+};
 use rand::Rng;
 
 pub mod constraints;
+pub mod storage;
+
+use self::storage::{Database, Patch};
+
+/// Identifies a client-registered checkpoint witness within a [`HistoryTree`].
+pub type WitnessId = u64;
+
+/// An incrementally-completed authentication path for the leaf appended at `epoch`.
+///
+/// `siblings` is indexed from the leaf upward, one entry per tree level. An entry is
+/// `Some` as soon as the sibling subtree at that level is fully determined: either it
+/// was already complete when the witness was registered (a "left" sibling), or a later
+/// `append_digest` call has since finished filling it in (a "right" sibling).
+struct EpochWitness<P: MerkleTreeParameters> {
+    epoch: MerkleIndex,
+    siblings: Vec<Option<<P::H as FixedLengthCRH>::Output>>,
+}
 
 pub struct HistoryTree<P: MerkleTreeParameters, D: Hash + ToBytes + Eq + Clone> {
+    // Still fed every appended leaf and never pruned: `lookup_path`/`lookup_history` must be
+    // able to produce an authentication path for an arbitrary *non-witnessed* historical
+    // epoch (a caller's saved checkpoint need not have had `register_witness` called for
+    // it), which `frontier`/`witnesses` alone cannot reconstruct once later appends have
+    // folded that epoch's siblings away. `prune()` therefore only ever reduces `digest_d`/
+    // `epoch_digests`, not this field -- it does not address the tree's dominant memory
+    // cost, only the redundant per-digest index on top of it.
     tree: SparseMerkleTree<P>,
     digest_d: HashMap<D, MerkleIndex>,
+    // Forward epoch -> digest lookup, used to assemble the leaf list for
+    // `range_proof`. Like `digest_d`, it only ever holds live (un-pruned) epochs.
+    epoch_digests: HashMap<MerkleIndex, D>,
     epoch: MerkleIndex,
+    // Rightmost filled subtree root at each level (the classic incremental-accumulator
+    // "frontier"), tagged with the block index it covers: used to read off a witness's
+    // already-fixed left siblings, to recognize when a witness's pending right sibling has
+    // just completed, and (via `known_subtree_root`) to answer `range_proof` boundary
+    // queries about completed subtrees without needing their pruned leaves.
+    frontier: Vec<Option<(MerkleIndex, <P::H as FixedLengthCRH>::Output)>>,
+    // Left siblings fixed by the most recent `append_digest` call. A witness can only
+    // be registered for that epoch, since earlier left siblings are folded into
+    // higher levels (and dropped) by subsequent appends.
+    last_append_siblings: Vec<Option<<P::H as FixedLengthCRH>::Output>>,
+    witnesses: HashMap<WitnessId, EpochWitness<P>>,
+    next_witness_id: WitnessId,
+    // Root hash of a fully-default (unwritten) subtree at each level, precomputed once
+    // so `range_proof` can account for the not-yet-appended tail of the tree in O(1).
+    default_hashes: Vec<<P::H as FixedLengthCRH>::Output>,
 }
 
 impl<P: MerkleTreeParameters, D: Hash + ToBytes + Eq + Clone> HistoryTree<P, D> {
-    pub fn new(hash_parameters: &<P::H as FixedLengthCRH>::Parameters) -> Result<Self, Error> {
+    /// Builds a fresh, empty history tree that persists its accumulator state (frontier
+    /// node hashes and the digest -> epoch index) into `db` as it grows.
+    //TODO: This is forward-looking durability only -- `db` protects a running process
+    //against losing an in-flight append, it does not yet let a *new* process resume a
+    //non-empty tree. Resuming would need `epoch`, `tree` (the opaque `SparseMerkleTree`,
+    //which has no pluggable storage of its own), and `epoch_digests` reloaded too, none of
+    //which `db` carries today; the frontier alone isn't enough to safely continue
+    //appending. Until that's implemented, `new` always starts from epoch 0, and wipes `db`
+    //entirely (not just frontier nodes) so that a previous instance's `digest -> epoch`
+    //entries can't linger and be returned by this tree's `lookup_digest` db fallback for an
+    //epoch number that's now been reused from scratch.
+    pub fn new<DB: Database>(
+        hash_parameters: &<P::H as FixedLengthCRH>::Parameters,
+        db: &mut DB,
+    ) -> Result<Self, Error> {
+        db.clear()?;
+        let mut default_hashes = vec![<P::H as FixedLengthCRH>::evaluate(
+            hash_parameters,
+            &digest_to_bytes(&<[u8; 32]>::default())?
+                [..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+        )?];
+        for _ in 0..P::DEPTH {
+            let prev = default_hashes.last().unwrap().clone();
+            let mut buffer = [0_u8; 128];
+            let mut writer = Cursor::new(&mut buffer[..]);
+            prev.write(&mut writer)?;
+            prev.write(&mut writer)?;
+            default_hashes.push(<P::H as FixedLengthCRH>::evaluate(
+                hash_parameters,
+                &buffer[..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+            )?);
+        }
         Ok(HistoryTree {
             tree: SparseMerkleTree::<P>::new(&<[u8; 32]>::default(), hash_parameters)?,
             digest_d: HashMap::new(),
+            epoch_digests: HashMap::new(),
             epoch: 0,
+            frontier: vec![None; P::DEPTH as usize],
+            last_append_siblings: Vec::new(),
+            witnesses: HashMap::new(),
+            next_witness_id: 0,
+            default_hashes,
         })
     }
 
     // TODO: Manage digest lifetimes so as not to store clones
-    pub fn append_digest(&mut self, digest: &D) -> Result<(), Error> {
+    pub fn append_digest<DB: Database>(&mut self, digest: &D, db: &mut DB) -> Result<(), Error> {
         self.tree.update(self.epoch, &digest_to_bytes(digest)?)?;
+        self.epoch_digests.insert(self.epoch, digest.clone());
+        let mut patch = Patch::new();
+
+        let leaf_index = self.epoch;
+        let mut node = <P::H as FixedLengthCRH>::evaluate(
+            &self.tree.hash_parameters,
+            &digest_to_bytes(digest)?[..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+        )?;
+        // At every level whose bit in `leaf_index` is set, our own path's sibling is to the
+        // left and already complete: it is the frontier peak at that level.
+        let siblings: Vec<_> = (0..self.frontier.len())
+            .map(|level| {
+                if (leaf_index >> level) & 1 == 1 {
+                    self.frontier[level].clone().map(|(_, hash)| hash)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        for level in 0..self.frontier.len() {
+            // A subtree of `2^level` leaves ending at `leaf_index + 1` has just completed,
+            // with root `node`. Any pending witness whose sibling at this level is exactly
+            // that subtree can now have its slot filled in.
+            let completed_block = leaf_index >> level;
+            for witness in self.witnesses.values_mut() {
+                if witness.siblings[level].is_none() {
+                    let witness_block = witness.epoch >> level;
+                    if witness_block % 2 == 0 && witness_block + 1 == completed_block {
+                        witness.siblings[level] = Some(node.clone());
+                    }
+                }
+            }
+
+            match self.frontier[level].take() {
+                Some((_, left)) => {
+                    // This level's frontier slot is folded into the parent and cleared.
+                    patch.delete_node(level as u64);
+                    let mut buffer = [0_u8; 128];
+                    let mut writer = Cursor::new(&mut buffer[..]);
+                    left.write(&mut writer)?;
+                    node.write(&mut writer)?;
+                    node = <P::H as FixedLengthCRH>::evaluate(
+                        &self.tree.hash_parameters,
+                        &buffer[..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+                    )?;
+                }
+                None => {
+                    let mut node_bytes = Vec::new();
+                    node.write(&mut node_bytes)?;
+                    patch.put_node(level as u64, node_bytes);
+                    self.frontier[level] = Some((completed_block, node));
+                    break;
+                }
+            }
+        }
+        self.last_append_siblings = siblings;
+
+        let mut digest_bytes = Vec::new();
+        digest.write(&mut digest_bytes)?;
+        patch.put_digest(digest_bytes, self.epoch);
+        // Every frontier and digest-index write this append produced is applied as one
+        // atomic commit, so a crash can't leave `db` with only part of an append visible.
+        db.commit(patch)?;
+
         self.digest_d.insert(digest.clone(), self.epoch);
         self.epoch += 1;
         Ok(())
@@ -40,9 +187,243 @@ impl<P: MerkleTreeParameters, D: Hash + ToBytes + Eq + Clone> HistoryTree<P, D>
         self.tree.lookup(epoch)
     }
 
-    pub fn lookup_digest(&self, digest: &D) -> Option<MerkleIndex> {
-        self.digest_d.get(digest).cloned()
+    /// Looks up the epoch a digest was appended at, checking the in-memory index first and
+    /// falling back to `db`. `append_digest` writes both and `prune` trims both, and `new`
+    /// clears `db`, so the fallback does not recover entries from an earlier instance.
+    pub fn lookup_digest<DB: Database>(&self, digest: &D, db: &DB) -> Result<Option<MerkleIndex>, Error> {
+        if let Some(epoch) = self.digest_d.get(digest).cloned() {
+            return Ok(Some(epoch));
+        }
+        let mut digest_bytes = Vec::new();
+        digest.write(&mut digest_bytes)?;
+        Ok(db.get_epoch(&digest_bytes))
+    }
+
+    /// Registers a witness for `epoch`, which must be the epoch most recently passed to
+    /// `append_digest` -- earlier epochs' left siblings have already been folded away.
+    pub fn register_witness(&mut self, epoch: MerkleIndex) -> Result<WitnessId, Error> {
+        if self.epoch == 0 || epoch != self.epoch - 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "can only register a witness for the most recently appended epoch",
+            )
+            .into());
+        }
+        let witness_id = self.next_witness_id;
+        self.witnesses.insert(
+            witness_id,
+            EpochWitness {
+                epoch,
+                siblings: self.last_append_siblings.clone(),
+            },
+        );
+        self.next_witness_id += 1;
+        Ok(witness_id)
+    }
+
+    /// Returns the authentication path for a registered witness's epoch against the current
+    /// root, without reading `tree`: completed siblings come from `EpochWitness::siblings`,
+    /// and a right sibling that is still being filled is hashed from `frontier` and the
+    /// default hashes. Neither source is removed by `prune()`.
+    pub fn witness_path(&self, witness_id: WitnessId) -> Result<MerkleTreePath<P>, Error> {
+        let witness = self.witnesses.get(&witness_id).ok_or_else(|| {
+            Error::from(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no witness registered with this id",
+            ))
+        })?;
+        let path = witness
+            .siblings
+            .iter()
+            .enumerate()
+            .map(|(level, sibling)| match sibling {
+                Some(hash) => Ok(hash.clone()),
+                None => self.subtree_hash(level as u32, (witness.epoch >> level) ^ 1),
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        Ok(MerkleTreePath { path })
+    }
+
+    /// Drops all per-epoch digest bookkeeping (in memory and in `db`) that isn't backing a
+    /// live witness. This does not shrink `tree`, which still holds every appended leaf --
+    /// see the field comment on [`HistoryTree::tree`] for why `lookup_path` still needs it.
+    pub fn prune<DB: Database>(&mut self, db: &mut DB) -> Result<(), Error> {
+        let live_epochs: HashSet<MerkleIndex> =
+            self.witnesses.values().map(|witness| witness.epoch).collect();
+        let mut patch = Patch::new();
+        for (digest, epoch) in self.digest_d.iter() {
+            if !live_epochs.contains(epoch) {
+                let mut digest_bytes = Vec::new();
+                digest.write(&mut digest_bytes)?;
+                patch.delete_digest(digest_bytes);
+            }
+        }
+        db.commit(patch)?;
+        self.digest_d.retain(|_, epoch| live_epochs.contains(epoch));
+        self.epoch_digests.retain(|epoch, _| live_epochs.contains(epoch));
+        Ok(())
+    }
+
+    /// Builds a single multiproof covering every epoch in `[start_epoch, end_epoch]`:
+    /// the ordered leaf digests for that range, plus the minimal set of sibling hashes
+    /// needed to recompute `tree.root` from them (internal nodes shared by more than one
+    /// epoch's path are emitted only once).
+    pub fn range_proof(
+        &self,
+        start_epoch: MerkleIndex,
+        end_epoch: MerkleIndex,
+    ) -> Result<
+        (
+            Vec<D>,
+            Vec<(u32, MerkleIndex, <P::H as FixedLengthCRH>::Output)>,
+        ),
+        Error,
+    > {
+        if start_epoch > end_epoch || end_epoch >= self.epoch {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "range must be non-empty and fully within the appended epochs",
+            )
+            .into());
+        }
+        let leaf_digests = (start_epoch..=end_epoch)
+            .map(|epoch| {
+                self.epoch_digests.get(&epoch).cloned().ok_or_else(|| {
+                    Error::from(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "digest for an epoch in range has been pruned",
+                    ))
+                })
+            })
+            .collect::<Result<Vec<D>, Error>>()?;
+
+        let mut siblings = Vec::new();
+        self.collect_range_siblings(P::DEPTH as u32, 0, start_epoch, end_epoch, &mut siblings)?;
+        Ok((leaf_digests, siblings))
+    }
+
+    // Classifies the subtree of `2^level` leaves starting at `block_index * 2^level`
+    // against `[start_epoch, end_epoch]`: `Covered` if it lies entirely inside the range
+    // (the verifier can recompute it from the supplied leaves without our help) or has
+    // been fully resolved by recursing into its children; `Disjoint` if it lies entirely
+    // outside and the caller must request its hash as a sibling.
+    fn collect_range_siblings(
+        &self,
+        level: u32,
+        block_index: MerkleIndex,
+        start_epoch: MerkleIndex,
+        end_epoch: MerkleIndex,
+        siblings: &mut Vec<(u32, MerkleIndex, <P::H as FixedLengthCRH>::Output)>,
+    ) -> Result<RangeCoverage, Error> {
+        let size = 1_u64 << level;
+        let lo = block_index * size;
+        let hi = lo + size;
+        if hi <= start_epoch || lo > end_epoch {
+            return Ok(RangeCoverage::Disjoint);
+        }
+        if lo >= start_epoch && hi - 1 <= end_epoch {
+            return Ok(RangeCoverage::Covered);
+        }
+        let left_block = block_index * 2;
+        let right_block = left_block + 1;
+        let left =
+            self.collect_range_siblings(level - 1, left_block, start_epoch, end_epoch, siblings)?;
+        let right = self.collect_range_siblings(
+            level - 1,
+            right_block,
+            start_epoch,
+            end_epoch,
+            siblings,
+        )?;
+        if let RangeCoverage::Disjoint = left {
+            siblings.push((level - 1, left_block, self.subtree_hash(level - 1, left_block)?));
+        }
+        if let RangeCoverage::Disjoint = right {
+            siblings.push((
+                level - 1,
+                right_block,
+                self.subtree_hash(level - 1, right_block)?,
+            ));
+        }
+        Ok(RangeCoverage::Covered)
+    }
+
+    // Root of a subtree that's already known without touching (possibly pruned) leaves:
+    // either the current frontier peak at `level` (if it covers exactly `block_index`), or
+    // a live witness's sibling slot at `level` (if that sibling is exactly `block_index`).
+    // Both sources survive `prune()`, so this lets `subtree_hash` serve boundary siblings
+    // for old, unwitnessed-and-pruned regions whenever the subtree itself is still pinned
+    // by the accumulator or by some other witness.
+    fn known_subtree_root(
+        &self,
+        level: u32,
+        block_index: MerkleIndex,
+    ) -> Option<<P::H as FixedLengthCRH>::Output> {
+        if let Some((frontier_block, hash)) = &self.frontier[level as usize] {
+            if *frontier_block == block_index {
+                return Some(hash.clone());
+            }
+        }
+        for witness in self.witnesses.values() {
+            if let Some(hash) = &witness.siblings[level as usize] {
+                let witness_block = witness.epoch >> level;
+                let sibling_block = if witness_block % 2 == 1 {
+                    witness_block - 1
+                } else {
+                    witness_block + 1
+                };
+                if sibling_block == block_index {
+                    return Some(hash.clone());
+                }
+            }
+        }
+        None
     }
+
+    // Root hash of the subtree of `2^level` leaves starting at `block_index * 2^level`:
+    // served from `known_subtree_root` when possible, falling back to rehashing from
+    // `epoch_digests` (or `default_hashes` for the not-yet-appended tail) otherwise.
+    fn subtree_hash(
+        &self,
+        level: u32,
+        block_index: MerkleIndex,
+    ) -> Result<<P::H as FixedLengthCRH>::Output, Error> {
+        let size = 1_u64 << level;
+        let lo = block_index * size;
+        if lo >= self.epoch {
+            return Ok(self.default_hashes[level as usize].clone());
+        }
+        if let Some(hash) = self.known_subtree_root(level, block_index) {
+            return Ok(hash);
+        }
+        if level == 0 {
+            let digest = self.epoch_digests.get(&block_index).ok_or_else(|| {
+                Error::from(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "digest for an epoch in range has been pruned",
+                ))
+            })?;
+            return <P::H as FixedLengthCRH>::evaluate(
+                &self.tree.hash_parameters,
+                &digest_to_bytes(digest)?[..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+            );
+        }
+        let left = self.subtree_hash(level - 1, block_index * 2)?;
+        let right = self.subtree_hash(level - 1, block_index * 2 + 1)?;
+        let mut buffer = [0_u8; 128];
+        let mut writer = Cursor::new(&mut buffer[..]);
+        left.write(&mut writer)?;
+        right.write(&mut writer)?;
+        <P::H as FixedLengthCRH>::evaluate(
+            &self.tree.hash_parameters,
+            &buffer[..(<P::H as FixedLengthCRH>::INPUT_SIZE_BITS / 8)],
+        )
+    }
+}
+
+enum RangeCoverage {
+    Covered,
+    Disjoint,
 }
 
 pub struct SingleStepUpdateProof<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters>
@@ -70,10 +451,74 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> Default for SingleSte
     }
 }
 
-pub struct SingleStepAVDWithHistory<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters>{
+/// Combines an SSAVD digest, a history-tree root, and the current epoch into the single
+/// final digest exposed to clients. Parameterizing `SingleStepAVDWithHistory` over this
+/// trait lets callers pick a combiner whose native `evaluate` and circuit `constraints`
+/// (see the `constraints` module) best match the hash function in use, instead of being
+/// stuck with [`hash_to_final_digest`]'s two-hash Pedersen workaround.
+pub trait DigestCombiner<SSAVD: SingleStepAVD, H: FixedLengthCRH> {
+    fn evaluate(
+        parameters: &H::Parameters,
+        ssavd_digest: &SSAVD::Digest,
+        history_tree_digest: &H::Output,
+        epoch: &u64,
+    ) -> Result<H::Output, Error>;
+}
+
+/// Default combiner, kept for backward compatibility: folds in the epoch with a second
+/// evaluation of `H`, exactly as [`hash_to_final_digest`] always has.
+pub struct PedersenDigestCombiner;
+
+impl<SSAVD: SingleStepAVD, H: FixedLengthCRH> DigestCombiner<SSAVD, H> for PedersenDigestCombiner {
+    fn evaluate(
+        parameters: &H::Parameters,
+        ssavd_digest: &SSAVD::Digest,
+        history_tree_digest: &H::Output,
+        epoch: &u64,
+    ) -> Result<H::Output, Error> {
+        hash_to_final_digest::<SSAVD, H>(parameters, ssavd_digest, history_tree_digest, epoch)
+    }
+}
+
+/// Poseidon-style sponge combiner: absorbs the SSAVD digest, the history-tree root, and
+/// the epoch and squeezes the final digest with a single call to `H::evaluate`, instead
+/// of the two passes `PedersenDigestCombiner` needs -- roughly halving the update-circuit
+/// constraints when `H` is a Poseidon permutation.
+pub struct PoseidonDigestCombiner;
+
+impl<SSAVD: SingleStepAVD, H: FixedLengthCRH> DigestCombiner<SSAVD, H> for PoseidonDigestCombiner {
+    fn evaluate(
+        parameters: &H::Parameters,
+        ssavd_digest: &SSAVD::Digest,
+        history_tree_digest: &H::Output,
+        epoch: &u64,
+    ) -> Result<H::Output, Error> {
+        let mut buffer = Vec::new();
+        ssavd_digest.write(&mut buffer)?;
+        history_tree_digest.write(&mut buffer)?;
+        buffer.write_all(&epoch.to_le_bytes())?;
+        let input_bytes = H::INPUT_SIZE_BITS / 8;
+        if buffer.len() > input_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "ssavd digest, history-tree digest, and epoch do not fit in a single hash input -- pick an H with a larger input size",
+            )
+            .into());
+        }
+        buffer.resize(input_bytes, 0);
+        H::evaluate(&parameters, &buffer)
+    }
+}
+
+pub struct SingleStepAVDWithHistory<
+    SSAVD: SingleStepAVD,
+    HTParams: MerkleTreeParameters,
+    C: DigestCombiner<SSAVD, HTParams::H> = PedersenDigestCombiner,
+> {
     ssavd: SSAVD,
     history_tree: HistoryTree<HTParams, <HTParams::H as FixedLengthCRH>::Output>,
     digest: <HTParams::H as FixedLengthCRH>::Output,
+    _combiner: std::marker::PhantomData<C>,
 }
 
 #[derive(Clone, Eq, PartialEq, Hash)]
@@ -88,13 +533,37 @@ pub struct LookupProof<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> {
     history_tree_digest: <HTParams::H as FixedLengthCRH>::Output,
 }
 
+/// A single proof covering lookups for every key in `keys`, in place of `keys.len()`
+/// independent `LookupProof`s: the per-key SSAVD sub-proofs plus one shared `ssavd_digest`
+/// and `history_tree_digest`, since every key is looked up against the same snapshot.
+pub struct BatchLookupProof<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> {
+    ssavd_proofs: Vec<SSAVD::LookupProof>,
+    ssavd_digest: SSAVD::Digest,
+    history_tree_digest: <HTParams::H as FixedLengthCRH>::Output,
+}
+
 pub struct HistoryProof<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> {
     history_tree_proof: MerkleTreePath<HTParams>,
     ssavd_digest: SSAVD::Digest,
     history_tree_digest: <HTParams::H as FixedLengthCRH>::Output,
 }
 
-impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHistory<SSAVD, HTParams> {
+/// A single multiproof covering every epoch in `[start_epoch, end_epoch]`, in place of
+/// `(end_epoch - start_epoch + 1)` independent `HistoryProof`s: one ordered list of leaf
+/// digests plus the minimal set of sibling hashes needed to recompute `history_tree_digest`,
+/// as built by [`HistoryTree::range_proof`].
+pub struct RangeHistoryProof<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> {
+    start_epoch: MerkleIndex,
+    end_epoch: MerkleIndex,
+    leaf_digests: Vec<<HTParams::H as FixedLengthCRH>::Output>,
+    siblings: Vec<(u32, MerkleIndex, <HTParams::H as FixedLengthCRH>::Output)>,
+    ssavd_digest: SSAVD::Digest,
+    history_tree_digest: <HTParams::H as FixedLengthCRH>::Output,
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters, C: DigestCombiner<SSAVD, HTParams::H>>
+    SingleStepAVDWithHistory<SSAVD, HTParams, C>
+{
 
     pub fn setup<R: Rng>(rng: &mut R)
         -> Result<(SSAVD::PublicParameters, <HTParams::H as FixedLengthCRH>::Parameters), Error> {
@@ -106,14 +575,15 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
 
     //TODO: Double storing SSAVD public parameters (also stored in MerkleTreeAVD)
     //TODO: Double storing hash parameters if shared across SSAVD and history tree
-    pub fn new<R: Rng>(
+    pub fn new<R: Rng, DB: Database>(
         rng: &mut R,
         ssavd_pp: &SSAVD::PublicParameters,
         history_tree_parameters: &<HTParams::H as FixedLengthCRH>::Parameters,
+        db: &mut DB,
     ) -> Result<Self, Error>{
         let ssavd = SSAVD::new(rng, ssavd_pp)?;
-        let history_tree = HistoryTree::new(history_tree_parameters)?;
-        let digest = hash_to_final_digest::<SSAVD, HTParams::H>(
+        let history_tree = HistoryTree::new(history_tree_parameters, db)?;
+        let digest = C::evaluate(
             history_tree_parameters,
             &ssavd.digest()?,
             &history_tree.tree.root,
@@ -123,6 +593,7 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
             ssavd: ssavd,
             history_tree: history_tree,
             digest: digest,
+            _combiner: std::marker::PhantomData,
         })
     }
 
@@ -133,20 +604,38 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
         }
     }
 
-    pub fn update(
+    /// Registers a client-maintained witness for the epoch most recently appended to the
+    /// history tree, so that epoch's digest bookkeeping is kept by later `prune()` calls
+    /// and `witness_path` can still produce its authentication path.
+    pub fn register_witness(&mut self, epoch: u64) -> Result<WitnessId, Error> {
+        self.history_tree.register_witness(epoch)
+    }
+
+    pub fn witness_path(&self, witness_id: WitnessId) -> Result<MerkleTreePath<HTParams>, Error> {
+        self.history_tree.witness_path(witness_id)
+    }
+
+    /// See [`HistoryTree::prune`]: drops digest bookkeeping for epochs with no live witness,
+    /// not the underlying history tree itself.
+    pub fn prune<DB: Database>(&mut self, db: &mut DB) -> Result<(), Error> {
+        self.history_tree.prune(db)
+    }
+
+    pub fn update<DB: Database>(
         &mut self,
         key: &[u8; 32],
         value: &[u8; 32],
+        db: &mut DB,
     ) -> Result<SingleStepUpdateProof<SSAVD, HTParams>, Error>{
         let prev_ssavd_digest = self.ssavd.digest()?;
         let (new_ssavd_digest, ssavd_proof) = self.ssavd.update(key, value)?;
         let prev_epoch = self.history_tree.epoch.clone();
         let prev_digest = self.digest.clone();
-        self.history_tree.append_digest(&prev_digest)?;
+        self.history_tree.append_digest(&prev_digest, db)?;
         let history_tree_proof = self.history_tree.lookup_path(prev_epoch)?;
 
         // Update digest
-        self.digest = hash_to_final_digest::<SSAVD, HTParams::H>(
+        self.digest = C::evaluate(
             &self.history_tree.tree.hash_parameters,
             &new_ssavd_digest,
             &self.history_tree.tree.root,
@@ -164,19 +653,20 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
         })
     }
 
-    pub fn batch_update(
+    pub fn batch_update<DB: Database>(
         &mut self,
         kvs: &Vec<([u8; 32], [u8; 32])>,
+        db: &mut DB,
     ) -> Result<SingleStepUpdateProof<SSAVD, HTParams>, Error>{
         let prev_ssavd_digest = self.ssavd.digest()?;
         let (new_ssavd_digest, ssavd_proof) = self.ssavd.batch_update(kvs)?;
         let prev_epoch = self.history_tree.epoch.clone();
         let prev_digest = self.digest.clone();
-        self.history_tree.append_digest(&prev_digest)?;
+        self.history_tree.append_digest(&prev_digest, db)?;
         let history_tree_proof = self.history_tree.lookup_path(prev_epoch)?;
 
         // Update digest
-        self.digest = hash_to_final_digest::<SSAVD, HTParams::H>(
+        self.digest = C::evaluate(
             &self.history_tree.tree.hash_parameters,
             &new_ssavd_digest,
             &self.history_tree.tree.root,
@@ -220,7 +710,7 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
         Ok(
             SSAVD::verify_lookup(ssavd_pp, key, value, &proof.ssavd_digest, &proof.ssavd_proof)? &&
                 digest.digest ==
-                    hash_to_final_digest::<SSAVD, HTParams::H>(
+                    C::evaluate(
                         history_tree_pp,
                         &proof.ssavd_digest,
                         &proof.history_tree_digest,
@@ -229,12 +719,67 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
         )
     }
 
-    fn lookup_history(
+    /// Looks up every key in `keys` against the current snapshot, returning one
+    /// `BatchLookupProof` that stores the shared `ssavd_digest` and `history_tree_digest`
+    /// once rather than once per key.
+    fn batch_lookup(
+        &self,
+        keys: &[[u8; 32]],
+    ) -> Result<(Vec<Option<(u64, [u8; 32])>>, BatchLookupProof<SSAVD, HTParams>), Error> {
+        let ssavd_digest = self.ssavd.digest()?;
+        let mut values = Vec::with_capacity(keys.len());
+        let mut ssavd_proofs = Vec::with_capacity(keys.len());
+        for key in keys {
+            let (value, _, proof) = self.ssavd.lookup(key)?;
+            values.push(value);
+            ssavd_proofs.push(proof);
+        }
+        Ok((
+            values,
+            BatchLookupProof {
+                ssavd_proofs,
+                ssavd_digest,
+                history_tree_digest: self.history_tree.tree.root.clone(),
+            },
+        ))
+    }
+
+    /// Verifies a [`BatchLookupProof`]: `keys`, `values`, and `proof.ssavd_proofs` must be
+    /// the same length and agree positionally.
+    fn verify_batch_lookup(
+        ssavd_pp: &SSAVD::PublicParameters,
+        history_tree_pp: &<HTParams::H as FixedLengthCRH>::Parameters,
+        keys: &[[u8; 32]],
+        values: &[Option<(u64, [u8; 32])>],
+        digest: &Digest<HTParams>,
+        proof: &BatchLookupProof<SSAVD, HTParams>,
+    ) -> Result<bool, Error> {
+        if keys.len() != values.len() || keys.len() != proof.ssavd_proofs.len() {
+            return Ok(false);
+        }
+        for ((key, value), ssavd_proof) in keys.iter().zip(values).zip(&proof.ssavd_proofs) {
+            if !SSAVD::verify_lookup(ssavd_pp, key, value, &proof.ssavd_digest, ssavd_proof)? {
+                return Ok(false);
+            }
+        }
+        Ok(
+            digest.digest ==
+                C::evaluate(
+                    history_tree_pp,
+                    &proof.ssavd_digest,
+                    &proof.history_tree_digest,
+                    &digest.epoch,
+                )?
+        )
+    }
+
+    fn lookup_history<DB: Database>(
         &self,
         prev_digest: &Digest<HTParams>,
+        db: &DB,
     ) -> Result<Option<HistoryProof<SSAVD, HTParams>>, Error> {
         match (
-            self.history_tree.lookup_digest(&prev_digest.digest),
+            self.history_tree.lookup_digest(&prev_digest.digest, db)?,
             self.history_tree.lookup_path(prev_digest.epoch)?,
         ) {
             (Some(epoch), path) if epoch == prev_digest.epoch => {
@@ -262,7 +807,60 @@ impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> SingleStepAVDWithHist
                 history_tree_pp,
             )? &&
                 current_digest.digest ==
-                    hash_to_final_digest::<SSAVD, HTParams::H>(
+                    C::evaluate(
+                        history_tree_pp,
+                        &proof.ssavd_digest,
+                        &proof.history_tree_digest,
+                        &current_digest.epoch,
+                    )?
+        )
+    }
+
+    /// Builds a single proof covering every epoch in `[start_epoch, end_epoch]`, costing
+    /// roughly one path plus the boundary siblings instead of `(end_epoch - start_epoch + 1)`
+    /// independent `HistoryProof`s.
+    pub fn range_history_proof(
+        &self,
+        start_epoch: MerkleIndex,
+        end_epoch: MerkleIndex,
+    ) -> Result<RangeHistoryProof<SSAVD, HTParams>, Error> {
+        let (leaf_digests, siblings) = self.history_tree.range_proof(start_epoch, end_epoch)?;
+        Ok(RangeHistoryProof {
+            start_epoch,
+            end_epoch,
+            leaf_digests,
+            siblings,
+            ssavd_digest: self.ssavd.digest()?,
+            history_tree_digest: self.history_tree.tree.root.clone(),
+        })
+    }
+
+    /// Verifies a [`RangeHistoryProof`]: checks that `expected_digests` matches the proof's
+    /// leaf digests in order, that they and the supplied siblings recompute
+    /// `proof.history_tree_digest`, and that `current_digest` is bound to that root.
+    pub fn verify_range_history(
+        history_tree_pp: &<HTParams::H as FixedLengthCRH>::Parameters,
+        expected_digests: &[<HTParams::H as FixedLengthCRH>::Output],
+        current_digest: &Digest<HTParams>,
+        proof: &RangeHistoryProof<SSAVD, HTParams>,
+    ) -> Result<bool, Error> {
+        if proof.start_epoch > proof.end_epoch
+            || expected_digests.len() as u64 != proof.end_epoch - proof.start_epoch + 1
+            || expected_digests != proof.leaf_digests.as_slice()
+        {
+            return Ok(false);
+        }
+        let recomputed_root = reconstruct_range_root::<HTParams::H>(
+            history_tree_pp,
+            HTParams::DEPTH as u32,
+            proof.start_epoch,
+            &proof.leaf_digests,
+            &proof.siblings,
+        )?;
+        Ok(
+            recomputed_root == proof.history_tree_digest &&
+                current_digest.digest ==
+                    C::evaluate(
                         history_tree_pp,
                         &proof.ssavd_digest,
                         &proof.history_tree_digest,
@@ -302,6 +900,348 @@ pub fn digest_to_bytes<D: ToBytes>(digest: &D) -> Result<[u8; 128], Error> {
     Ok(buffer)
 }
 
+/// Rebuilds the covered subtree bottom-up from a [`RangeHistoryProof`]'s leaf digests and
+/// minimal sibling set, returning the recomputed root at `(depth, 0)`.
+fn reconstruct_range_root<H: FixedLengthCRH>(
+    parameters: &H::Parameters,
+    depth: u32,
+    start_epoch: MerkleIndex,
+    leaf_digests: &[H::Output],
+    siblings: &[(u32, MerkleIndex, H::Output)],
+) -> Result<H::Output, Error>
+where
+    H::Output: Eq + Clone,
+{
+    let mut known: HashMap<(u32, MerkleIndex), H::Output> = HashMap::new();
+    for (i, leaf) in leaf_digests.iter().enumerate() {
+        let epoch = start_epoch + i as u64;
+        let hash = H::evaluate(
+            parameters,
+            &digest_to_bytes(leaf)?[..(H::INPUT_SIZE_BITS / 8)],
+        )?;
+        known.insert((0, epoch), hash);
+    }
+    for (level, block_index, hash) in siblings {
+        known.insert((*level, *block_index), hash.clone());
+    }
+    reconstruct_range_node::<H>(parameters, depth, 0, &mut known)
+}
+
+fn reconstruct_range_node<H: FixedLengthCRH>(
+    parameters: &H::Parameters,
+    level: u32,
+    block_index: MerkleIndex,
+    known: &mut HashMap<(u32, MerkleIndex), H::Output>,
+) -> Result<H::Output, Error>
+where
+    H::Output: Eq + Clone,
+{
+    if let Some(hash) = known.get(&(level, block_index)) {
+        return Ok(hash.clone());
+    }
+    if level == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "range proof is missing a required leaf or sibling hash",
+        )
+        .into());
+    }
+    let left = reconstruct_range_node::<H>(parameters, level - 1, block_index * 2, known)?;
+    let right = reconstruct_range_node::<H>(parameters, level - 1, block_index * 2 + 1, known)?;
+    let mut buffer = [0_u8; 128];
+    let mut writer = Cursor::new(&mut buffer[..]);
+    left.write(&mut writer)?;
+    right.write(&mut writer)?;
+    let combined = H::evaluate(parameters, &buffer[..(H::INPUT_SIZE_BITS / 8)])?;
+    known.insert((level, block_index), combined.clone());
+    Ok(combined)
+}
+
+// Canonical byte serialization for digests and proofs, so a vPKI server can ship them to
+// clients. The SSAVD sub-proofs are variable-size, so they are written behind an explicit
+// u64 length prefix; lists are written behind a u64 element count, and everything else
+// here is fixed-size and written directly.
+fn write_len_prefixed<T: ToBytes, W: Write>(value: &T, mut writer: W) -> IoResult<()> {
+    let mut buffer = Vec::new();
+    value.write(&mut buffer)?;
+    (buffer.len() as u64).write(&mut writer)?;
+    writer.write_all(&buffer)
+}
+
+// Sanity bound on a wire-supplied length prefix, well above any real proof produced by
+// this crate, so a corrupted or adversarial prefix can't force an unbounded allocation
+// before `read_exact` gets a chance to fail on a truncated buffer.
+const MAX_LEN_PREFIXED_BYTES: u64 = 16 * 1024 * 1024;
+
+fn read_len_prefixed<T: FromBytes, R: Read>(mut reader: R) -> IoResult<T> {
+    let len = u64::read(&mut reader)?;
+    if len > MAX_LEN_PREFIXED_BYTES {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "length-prefixed value exceeds maximum allowed size",
+        ));
+    }
+    let mut buffer = vec![0_u8; len as usize];
+    reader.read_exact(&mut buffer)?;
+    T::read(&buffer[..])
+}
+
+impl<HTParams: MerkleTreeParameters> ToBytes for Digest<HTParams>
+where
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.epoch.write(&mut writer)?;
+        self.digest.write(&mut writer)
+    }
+}
+
+impl<HTParams: MerkleTreeParameters> FromBytes for Digest<HTParams>
+where
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let epoch = u64::read(&mut reader)?;
+        let digest = <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        Ok(Self { epoch, digest })
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> ToBytes
+    for SingleStepUpdateProof<SSAVD, HTParams>
+where
+    SSAVD::UpdateProof: ToBytes,
+    SSAVD::Digest: ToBytes,
+    MerkleTreePath<HTParams>: ToBytes,
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        write_len_prefixed(&self.ssavd_proof, &mut writer)?;
+        self.history_tree_proof.write(&mut writer)?;
+        self.prev_ssavd_digest.write(&mut writer)?;
+        self.new_ssavd_digest.write(&mut writer)?;
+        self.prev_digest.write(&mut writer)?;
+        self.new_digest.write(&mut writer)?;
+        self.prev_epoch.write(&mut writer)
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> FromBytes
+    for SingleStepUpdateProof<SSAVD, HTParams>
+where
+    SSAVD::UpdateProof: FromBytes,
+    SSAVD::Digest: FromBytes,
+    MerkleTreePath<HTParams>: FromBytes,
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let ssavd_proof = read_len_prefixed(&mut reader)?;
+        let history_tree_proof = <MerkleTreePath<HTParams> as FromBytes>::read(&mut reader)?;
+        let prev_ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let new_ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let prev_digest = <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        let new_digest = <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        let prev_epoch = u64::read(&mut reader)?;
+        Ok(Self {
+            ssavd_proof,
+            history_tree_proof,
+            prev_ssavd_digest,
+            new_ssavd_digest,
+            prev_digest,
+            new_digest,
+            prev_epoch,
+        })
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> ToBytes for LookupProof<SSAVD, HTParams>
+where
+    SSAVD::LookupProof: ToBytes,
+    SSAVD::Digest: ToBytes,
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        write_len_prefixed(&self.ssavd_proof, &mut writer)?;
+        self.ssavd_digest.write(&mut writer)?;
+        self.history_tree_digest.write(&mut writer)
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> FromBytes
+    for LookupProof<SSAVD, HTParams>
+where
+    SSAVD::LookupProof: FromBytes,
+    SSAVD::Digest: FromBytes,
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let ssavd_proof = read_len_prefixed(&mut reader)?;
+        let ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let history_tree_digest =
+            <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        Ok(Self {
+            ssavd_proof,
+            ssavd_digest,
+            history_tree_digest,
+        })
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> ToBytes
+    for BatchLookupProof<SSAVD, HTParams>
+where
+    SSAVD::LookupProof: ToBytes,
+    SSAVD::Digest: ToBytes,
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        (self.ssavd_proofs.len() as u64).write(&mut writer)?;
+        for ssavd_proof in &self.ssavd_proofs {
+            write_len_prefixed(ssavd_proof, &mut writer)?;
+        }
+        self.ssavd_digest.write(&mut writer)?;
+        self.history_tree_digest.write(&mut writer)
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> FromBytes
+    for BatchLookupProof<SSAVD, HTParams>
+where
+    SSAVD::LookupProof: FromBytes,
+    SSAVD::Digest: FromBytes,
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let num_proofs = u64::read(&mut reader)?;
+        if num_proofs > MAX_LEN_PREFIXED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "batch lookup proof count exceeds maximum allowed size",
+            ));
+        }
+        let mut ssavd_proofs = Vec::with_capacity(num_proofs as usize);
+        for _ in 0..num_proofs {
+            ssavd_proofs.push(read_len_prefixed(&mut reader)?);
+        }
+        let ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let history_tree_digest =
+            <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        Ok(Self {
+            ssavd_proofs,
+            ssavd_digest,
+            history_tree_digest,
+        })
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> ToBytes
+    for HistoryProof<SSAVD, HTParams>
+where
+    MerkleTreePath<HTParams>: ToBytes,
+    SSAVD::Digest: ToBytes,
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.history_tree_proof.write(&mut writer)?;
+        self.ssavd_digest.write(&mut writer)?;
+        self.history_tree_digest.write(&mut writer)
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> FromBytes
+    for HistoryProof<SSAVD, HTParams>
+where
+    MerkleTreePath<HTParams>: FromBytes,
+    SSAVD::Digest: FromBytes,
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let history_tree_proof = <MerkleTreePath<HTParams> as FromBytes>::read(&mut reader)?;
+        let ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let history_tree_digest =
+            <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        Ok(Self {
+            history_tree_proof,
+            ssavd_digest,
+            history_tree_digest,
+        })
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> ToBytes
+    for RangeHistoryProof<SSAVD, HTParams>
+where
+    SSAVD::Digest: ToBytes,
+    <HTParams::H as FixedLengthCRH>::Output: ToBytes,
+{
+    fn write<W: Write>(&self, mut writer: W) -> IoResult<()> {
+        self.start_epoch.write(&mut writer)?;
+        self.end_epoch.write(&mut writer)?;
+        (self.leaf_digests.len() as u64).write(&mut writer)?;
+        for leaf_digest in &self.leaf_digests {
+            leaf_digest.write(&mut writer)?;
+        }
+        (self.siblings.len() as u64).write(&mut writer)?;
+        for (level, block_index, hash) in &self.siblings {
+            level.write(&mut writer)?;
+            block_index.write(&mut writer)?;
+            hash.write(&mut writer)?;
+        }
+        self.ssavd_digest.write(&mut writer)?;
+        self.history_tree_digest.write(&mut writer)
+    }
+}
+
+impl<SSAVD: SingleStepAVD, HTParams: MerkleTreeParameters> FromBytes
+    for RangeHistoryProof<SSAVD, HTParams>
+where
+    SSAVD::Digest: FromBytes,
+    <HTParams::H as FixedLengthCRH>::Output: FromBytes,
+{
+    fn read<R: Read>(mut reader: R) -> IoResult<Self> {
+        let start_epoch = MerkleIndex::read(&mut reader)?;
+        let end_epoch = MerkleIndex::read(&mut reader)?;
+        let num_leaves = u64::read(&mut reader)?;
+        if num_leaves > MAX_LEN_PREFIXED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "range history proof leaf count exceeds maximum allowed size",
+            ));
+        }
+        let mut leaf_digests = Vec::with_capacity(num_leaves as usize);
+        for _ in 0..num_leaves {
+            leaf_digests.push(<<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(
+                &mut reader,
+            )?);
+        }
+        let num_siblings = u64::read(&mut reader)?;
+        if num_siblings > MAX_LEN_PREFIXED_BYTES {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "range history proof sibling count exceeds maximum allowed size",
+            ));
+        }
+        let mut siblings = Vec::with_capacity(num_siblings as usize);
+        for _ in 0..num_siblings {
+            let level = u32::read(&mut reader)?;
+            let block_index = MerkleIndex::read(&mut reader)?;
+            let hash = <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+            siblings.push((level, block_index, hash));
+        }
+        let ssavd_digest = SSAVD::Digest::read(&mut reader)?;
+        let history_tree_digest =
+            <<HTParams::H as FixedLengthCRH>::Output as FromBytes>::read(&mut reader)?;
+        Ok(Self {
+            start_epoch,
+            end_epoch,
+            leaf_digests,
+            siblings,
+            ssavd_digest,
+            history_tree_digest,
+        })
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -318,6 +1258,7 @@ mod test {
         },
     };
     use crypto_primitives::sparse_merkle_tree::MerkleDepth;
+    use super::storage::MemoryDatabase;
 
     #[derive(Clone)]
     pub struct Window4x256;
@@ -348,13 +1289,40 @@ mod test {
 
     type TestMerkleTreeAVD = MerkleTreeAVD<MerkleTreeAVDTestParameters>;
     type TestAVDWithHistory = SingleStepAVDWithHistory<TestMerkleTreeAVD, MerkleTreeTestParameters>;
+    type TestAVDWithHistoryPoseidon = SingleStepAVDWithHistory<
+        TestMerkleTreeAVD,
+        MerkleTreeTestParameters,
+        PoseidonDigestCombiner,
+    >;
+
+    #[test]
+    fn poseidon_digest_combiner_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistoryPoseidon::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistoryPoseidon::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        let digest = avd.digest();
+
+        let (value, lookup_proof) = avd.lookup(&[1_u8; 32]).unwrap();
+        let result = TestAVDWithHistoryPoseidon::verify_lookup(
+            &ssavd_pp,
+            &crh_pp,
+            &[1_u8; 32],
+            &value,
+            &digest,
+            &lookup_proof,
+        ).unwrap();
+        assert!(result);
+    }
 
     #[test]
     fn lookup_test() {
         let mut rng = StdRng::seed_from_u64(0_u64);
         let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
-        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp).unwrap();
-        avd.update(&[1_u8; 32], &[2_u8; 32]).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
         let digest = avd.digest();
 
         let (value, lookup_proof) = avd.lookup(&[1_u8; 32]).unwrap();
@@ -369,19 +1337,56 @@ mod test {
         assert!(result);
     }
 
+    #[test]
+    fn batch_lookup_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        avd.update(&[3_u8; 32], &[4_u8; 32], &mut db).unwrap();
+        let digest = avd.digest();
+
+        let keys = vec![[1_u8; 32], [3_u8; 32], [5_u8; 32]];
+        let (values, proof) = avd.batch_lookup(&keys).unwrap();
+        let result = TestAVDWithHistory::verify_batch_lookup(
+            &ssavd_pp,
+            &crh_pp,
+            &keys,
+            &values,
+            &digest,
+            &proof,
+        ).unwrap();
+        assert!(result);
+
+        // Tampering with a returned value should fail verification.
+        let mut wrong_values = values.clone();
+        wrong_values[0] = None;
+        let result = TestAVDWithHistory::verify_batch_lookup(
+            &ssavd_pp,
+            &crh_pp,
+            &keys,
+            &wrong_values,
+            &digest,
+            &proof,
+        ).unwrap();
+        assert!(!result);
+    }
+
     #[test]
     fn history_test() {
         let mut rng = StdRng::seed_from_u64(0_u64);
         let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
-        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp).unwrap();
-        avd.update(&[1_u8; 32], &[2_u8; 32]).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
         let prev_digest = avd.digest();
         assert_eq!(prev_digest.epoch, 1);
-        avd.update(&[1_u8; 32], &[3_u8; 32]).unwrap();
+        avd.update(&[1_u8; 32], &[3_u8; 32], &mut db).unwrap();
         let curr_digest = avd.digest();
         assert_eq!(curr_digest.epoch, 2);
 
-        let history_proof = avd.lookup_history(&prev_digest).unwrap().unwrap();
+        let history_proof = avd.lookup_history(&prev_digest, &db).unwrap().unwrap();
         let result = TestAVDWithHistory::verify_history(
             &crh_pp,
             &prev_digest,
@@ -391,9 +1396,213 @@ mod test {
         assert!(result);
 
         let invalid_history_proof = avd.lookup_history(
-            &Digest{epoch: 1, digest: Default::default()}
+            &Digest{epoch: 1, digest: Default::default()},
+            &db,
         ).unwrap();
         assert!(invalid_history_proof.is_none());
     }
 
+    #[test]
+    fn witness_prune_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        let prev_digest = avd.digest();
+        assert_eq!(prev_digest.epoch, 1);
+        // `prev_digest` is only appended into the history tree by the *next* update, so
+        // registering a witness for it now is rejected.
+        assert!(avd.register_witness(prev_digest.epoch).is_err());
+
+        avd.update(&[1_u8; 32], &[3_u8; 32], &mut db).unwrap();
+        let witness_id = avd.register_witness(prev_digest.epoch).unwrap();
+
+        for i in 0..5_u8 {
+            avd.update(&[1_u8; 32], &[i; 32], &mut db).unwrap();
+        }
+        let curr_digest = avd.digest();
+
+        avd.prune(&mut db).unwrap();
+        // The witnessed epoch keeps its digest-lookup entry through `prune()`.
+        assert!(avd.lookup_history(&prev_digest, &db).unwrap().is_some());
+
+        let witnessed_path = avd.witness_path(witness_id).unwrap();
+        let history_proof = HistoryProof {
+            history_tree_proof: witnessed_path,
+            ssavd_digest: avd.ssavd.digest().unwrap(),
+            history_tree_digest: avd.history_tree.tree.root.clone(),
+        };
+        let result = TestAVDWithHistory::verify_history(
+            &crh_pp,
+            &prev_digest,
+            &curr_digest,
+            &history_proof,
+        ).unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn range_history_proof_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+
+        // `digests[e]` is the digest appended into the history tree at epoch `e`.
+        let mut digests = vec![avd.digest().digest];
+        for i in 0..5_u8 {
+            avd.update(&[1_u8; 32], &[i; 32], &mut db).unwrap();
+            digests.push(avd.digest().digest);
+        }
+        let curr_digest = avd.digest();
+
+        let proof = avd.range_history_proof(1, 3).unwrap();
+        let result = TestAVDWithHistory::verify_range_history(
+            &crh_pp,
+            &digests[1..=3],
+            &curr_digest,
+            &proof,
+        ).unwrap();
+        assert!(result);
+
+        // Tampering with an expected digest should fail verification.
+        let mut wrong_digests = digests[1..=3].to_vec();
+        wrong_digests[0] = Default::default();
+        let result = TestAVDWithHistory::verify_range_history(
+            &crh_pp,
+            &wrong_digests,
+            &curr_digest,
+            &proof,
+        ).unwrap();
+        assert!(!result);
+    }
+
+    #[test]
+    fn range_history_proof_survives_unrelated_prune_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+
+        // `digests[e]` is the digest appended into the history tree at epoch `e`.
+        let mut digests = vec![avd.digest().digest];
+        for i in 0..8_u8 {
+            avd.update(&[1_u8; 32], &[i; 32], &mut db).unwrap();
+            digests.push(avd.digest().digest);
+            // Witness epochs 4..=7 -- the range we'll prove below -- right as each becomes
+            // the most recently appended epoch, so they survive the `prune()` below.
+            if i >= 4 {
+                avd.register_witness(i as u64).unwrap();
+            }
+        }
+        let curr_digest = avd.digest();
+
+        avd.prune(&mut db).unwrap();
+        // Epochs 0..=3 were never witnessed, so their digests are gone.
+        assert!(avd.lookup_history(
+            &Digest { epoch: 0, digest: digests[0].clone() },
+            &db,
+        ).unwrap().is_none());
+
+        // The range [4, 7] is itself fully live, but its boundary sibling at the tree's
+        // (level 2, block 0) position covers the now-pruned epochs 0..=3. That sibling must
+        // come from a live witness's stored sibling rather than a rehash of those pruned leaves.
+        let proof = avd.range_history_proof(4, 7).unwrap();
+        let result = TestAVDWithHistory::verify_range_history(
+            &crh_pp,
+            &digests[4..=7],
+            &curr_digest,
+            &proof,
+        ).unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn lookup_proof_serialization_round_trip_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        let digest = avd.digest();
+        let (value, lookup_proof) = avd.lookup(&[1_u8; 32]).unwrap();
+
+        let mut bytes = vec![];
+        lookup_proof.write(&mut bytes).unwrap();
+        let deserialized_proof =
+            <LookupProof<TestMerkleTreeAVD, MerkleTreeTestParameters>>::read(&bytes[..]).unwrap();
+
+        let result = TestAVDWithHistory::verify_lookup(
+            &ssavd_pp,
+            &crh_pp,
+            &[1_u8; 32],
+            &value,
+            &digest,
+            &deserialized_proof,
+        ).unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn history_proof_serialization_round_trip_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        let prev_digest = avd.digest();
+        avd.update(&[1_u8; 32], &[3_u8; 32], &mut db).unwrap();
+        let curr_digest = avd.digest();
+        let history_proof = avd.lookup_history(&prev_digest, &db).unwrap().unwrap();
+
+        let mut bytes = vec![];
+        history_proof.write(&mut bytes).unwrap();
+        let deserialized_proof =
+            <HistoryProof<TestMerkleTreeAVD, MerkleTreeTestParameters>>::read(&bytes[..]).unwrap();
+
+        let result = TestAVDWithHistory::verify_history(
+            &crh_pp,
+            &prev_digest,
+            &curr_digest,
+            &deserialized_proof,
+        ).unwrap();
+        assert!(result);
+    }
+
+    #[test]
+    fn digest_serialization_round_trip_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+        let digest = avd.digest();
+
+        let mut bytes = vec![];
+        digest.write(&mut bytes).unwrap();
+        let deserialized_digest =
+            <Digest<MerkleTreeTestParameters>>::read(&bytes[..]).unwrap();
+        assert!(digest == deserialized_digest);
+    }
+
+    #[test]
+    fn update_proof_serialization_round_trip_test() {
+        let mut rng = StdRng::seed_from_u64(0_u64);
+        let (ssavd_pp, crh_pp) = TestAVDWithHistory::setup(&mut rng).unwrap();
+        let mut db = MemoryDatabase::new();
+        let mut avd = TestAVDWithHistory::new(&mut rng, &ssavd_pp, &crh_pp, &mut db).unwrap();
+        let update_proof = avd.update(&[1_u8; 32], &[2_u8; 32], &mut db).unwrap();
+
+        let mut bytes = vec![];
+        update_proof.write(&mut bytes).unwrap();
+        let deserialized_proof = <SingleStepUpdateProof<TestMerkleTreeAVD, MerkleTreeTestParameters>>::read(
+            &bytes[..],
+        ).unwrap();
+
+        assert!(deserialized_proof.prev_epoch == update_proof.prev_epoch);
+        assert!(deserialized_proof.new_digest == update_proof.new_digest);
+    }
+
 }
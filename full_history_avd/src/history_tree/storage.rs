@@ -0,0 +1,196 @@
+use crate::Error;
+use crypto_primitives::sparse_merkle_tree::MerkleIndex;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+};
+
+/// Persistent storage abstraction for the state [`super::HistoryTree`] accumulates across
+/// `append_digest` calls, modeled on the `Database`/`PatchSet` split used by zksync-era's
+/// `RocksDBWrapper`: a `Database` exposes point reads, and every write produced by one
+/// accumulator operation is assembled into a single [`Patch`] and applied with one atomic
+/// [`Database::commit`], so a single append can't be left half-written if a process dies
+/// mid-operation.
+///
+/// The two key spaces are the accumulator-level state `HistoryTree` writes on every append:
+/// frontier node hashes (keyed by level) and the digest -> epoch index backing
+/// `lookup_digest`. This is forward-looking durability only, scoped to a single running
+/// `HistoryTree` instance -- see the caveats on [`super::HistoryTree::new`] for what a
+/// genuine restart (a fresh process resuming a non-empty tree from `db`) would still need.
+pub trait Database {
+    /// Looks up the epoch a digest was appended at, if any.
+    fn get_epoch(&self, digest: &[u8]) -> Option<MerkleIndex>;
+    /// Atomically applies every write and delete recorded in `patch`.
+    fn commit(&mut self, patch: Patch) -> Result<(), Error>;
+    /// Wipes every node and digest entry. Used by [`super::HistoryTree::new`] so a fresh
+    /// tree never starts out reading stale state a previous instance left behind in `db`
+    /// (in particular stale `digest -> epoch` entries, which `lookup_digest`'s db fallback
+    /// would otherwise return for the new tree's epoch 0).
+    fn clear(&mut self) -> Result<(), Error>;
+}
+
+/// A batch of node and digest writes/deletes collected by one `HistoryTree` operation and
+/// applied to a [`Database`] as a single atomic unit.
+#[derive(Default)]
+pub struct Patch {
+    node_writes: HashMap<u64, Vec<u8>>,
+    node_deletes: HashSet<u64>,
+    digest_writes: HashMap<Vec<u8>, MerkleIndex>,
+    digest_deletes: HashSet<Vec<u8>>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn put_node(&mut self, level: u64, hash: Vec<u8>) {
+        self.node_deletes.remove(&level);
+        self.node_writes.insert(level, hash);
+    }
+
+    pub fn delete_node(&mut self, level: u64) {
+        self.node_writes.remove(&level);
+        self.node_deletes.insert(level);
+    }
+
+    pub fn put_digest(&mut self, digest: Vec<u8>, epoch: MerkleIndex) {
+        self.digest_deletes.remove(&digest);
+        self.digest_writes.insert(digest, epoch);
+    }
+
+    pub fn delete_digest(&mut self, digest: Vec<u8>) {
+        self.digest_writes.remove(&digest);
+        self.digest_deletes.insert(digest);
+    }
+}
+
+/// In-memory [`Database`] backed by a plain `HashMap` "patch set", suitable for tests and
+/// for callers that don't need accumulator state to outlive the process.
+#[derive(Default)]
+pub struct MemoryDatabase {
+    nodes: HashMap<u64, Vec<u8>>,
+    digests: HashMap<Vec<u8>, MerkleIndex>,
+}
+
+impl MemoryDatabase {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Database for MemoryDatabase {
+    fn get_epoch(&self, digest: &[u8]) -> Option<MerkleIndex> {
+        self.digests.get(digest).cloned()
+    }
+
+    fn commit(&mut self, patch: Patch) -> Result<(), Error> {
+        for level in patch.node_deletes {
+            self.nodes.remove(&level);
+        }
+        for (level, hash) in patch.node_writes {
+            self.nodes.insert(level, hash);
+        }
+        for digest in patch.digest_deletes {
+            self.digests.remove(&digest);
+        }
+        for (digest, epoch) in patch.digest_writes {
+            self.digests.insert(digest, epoch);
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        self.nodes.clear();
+        self.digests.clear();
+        Ok(())
+    }
+}
+
+/// RocksDB-backed [`Database`] for servers that want the frontier and digest index to outgrow
+/// available memory and to survive a process crash mid-append. Gated behind the `rocksdb`
+/// feature so the default build doesn't pull in the `rocksdb` dependency.
+///
+/// Note this alone doesn't make a `HistoryTree` resumable across a process restart --
+/// `HistoryTree::new` always starts from an empty tree regardless of backend; see its doc
+/// comment for what's still missing.
+#[cfg(feature = "rocksdb")]
+pub struct RocksDbDatabase {
+    db: rocksdb::DB,
+}
+
+#[cfg(feature = "rocksdb")]
+impl RocksDbDatabase {
+    const NODE_CF: &'static str = "nodes";
+    const DIGEST_CF: &'static str = "digests";
+
+    pub fn open(path: &std::path::Path) -> Result<Self, Error> {
+        let mut options = rocksdb::Options::default();
+        options.create_if_missing(true);
+        options.create_missing_column_families(true);
+        let db = rocksdb::DB::open_cf(&options, path, [Self::NODE_CF, Self::DIGEST_CF])
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+        Ok(Self { db })
+    }
+
+    fn node_cf(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(Self::NODE_CF)
+            .expect("node column family missing")
+    }
+
+    fn digest_cf(&self) -> &rocksdb::ColumnFamily {
+        self.db
+            .cf_handle(Self::DIGEST_CF)
+            .expect("digest column family missing")
+    }
+}
+
+#[cfg(feature = "rocksdb")]
+impl Database for RocksDbDatabase {
+    fn get_epoch(&self, digest: &[u8]) -> Option<MerkleIndex> {
+        self.db
+            .get_cf(self.digest_cf(), digest)
+            .ok()
+            .flatten()
+            .map(|bytes| {
+                let mut buf = [0_u8; 8];
+                buf.copy_from_slice(&bytes);
+                MerkleIndex::from_be_bytes(buf)
+            })
+    }
+
+    fn commit(&mut self, patch: Patch) -> Result<(), Error> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for (level, hash) in patch.node_writes {
+            batch.put_cf(self.node_cf(), level.to_be_bytes(), hash);
+        }
+        for level in patch.node_deletes {
+            batch.delete_cf(self.node_cf(), level.to_be_bytes());
+        }
+        for (digest, epoch) in patch.digest_writes {
+            batch.put_cf(self.digest_cf(), digest, epoch.to_be_bytes());
+        }
+        for digest in patch.digest_deletes {
+            batch.delete_cf(self.digest_cf(), digest);
+        }
+        self.db
+            .write(batch)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()).into())
+    }
+
+    fn clear(&mut self) -> Result<(), Error> {
+        let mut batch = rocksdb::WriteBatch::default();
+        for cf in [self.node_cf(), self.digest_cf()] {
+            for item in self.db.iterator_cf(cf, rocksdb::IteratorMode::Start) {
+                let (key, _) = item.map_err(|err| {
+                    io::Error::new(io::ErrorKind::Other, err.to_string())
+                })?;
+                batch.delete_cf(cf, key);
+            }
+        }
+        self.db
+            .write(batch)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()).into())
+    }
+}